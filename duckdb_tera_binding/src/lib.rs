@@ -1,10 +1,13 @@
+use std::collections::HashMap;
 use std::slice;
 use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
 
 use serde_json::Value;
 use tera::{Context, Tera};
 use std::error::Error;
-use std::ffi::{c_char, CString, CStr};
+use std::ffi::{c_char, c_void, CString, CStr};
 
 
 #[repr(C)]
@@ -13,12 +16,320 @@ pub enum ResultCString {
     Err(*mut c_char),
 }
 
+/// Length-explicit, binary-safe rendering result: `ptr`/`len`/`capacity`
+/// describe a `Vec<u8>` handed to the caller by value, with `is_error`
+/// distinguishing rendered output from an error message. Unlike
+/// `ResultCString` this never panics on interior NUL bytes and can carry
+/// arbitrary binary template output.
+#[repr(C)]
+pub struct ResultBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub capacity: usize,
+    pub is_error: bool,
+}
+
+/// Hands a `Vec<u8>` to the caller as a `ResultBuffer`, taking over its raw
+/// parts the same way `Vec::into_raw_parts` would.
+fn buffer_from_vec(mut data: Vec<u8>, is_error: bool) -> ResultBuffer {
+    let ptr = data.as_mut_ptr();
+    let len = data.len();
+    let capacity = data.capacity();
+    std::mem::forget(data);
+    ResultBuffer { ptr, len, capacity, is_error }
+}
+
+fn buffer_ok(data: Vec<u8>) -> ResultBuffer {
+    buffer_from_vec(data, false)
+}
+
+fn buffer_err(message: String) -> ResultBuffer {
+    buffer_from_vec(message.into_bytes(), true)
+}
+
+/// Builds a `ResultCString::Err`, falling back to a fixed message if
+/// `message` itself contains an interior NUL byte.
+fn cstring_err(message: String) -> ResultCString {
+    let c = CString::new(message)
+        .unwrap_or_else(|_| CString::new("error message contained an interior NUL byte").unwrap());
+    ResultCString::Err(c.into_raw())
+}
+
+/// Result of creating a persistent Tera engine: the allocated handle id on
+/// success, or an error string on failure.
+#[repr(C)]
+pub enum ResultHandle {
+    Ok(u64),
+    Err(*mut c_char),
+}
+
+/// Registry of live `Tera` instances, keyed by the handle id handed back to
+/// the caller from `tera_engine_create`. Keeping parsed engines here lets
+/// callers render thousands of times without re-globbing and re-parsing the
+/// template set on every call.
+///
+/// Each engine is wrapped in its own `Arc<Mutex<Tera>>` so the registry lock
+/// only has to be held long enough to look up or insert a handle; the
+/// (potentially slow) `render`/`add_raw_template`/`register_*` calls lock
+/// just that one engine, letting unrelated handles proceed concurrently.
+static TERA_ENGINES: LazyLock<Mutex<HashMap<u64, Arc<Mutex<Tera>>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_ENGINE_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+/// Locks the engine registry, recovering from poisoning instead of
+/// panicking, so a panic on one thread while the registry lock is held does
+/// not brick every other handle's access to it. The registry lock is never
+/// held during `tera.render`; a host callback that panics there poisons only
+/// that engine's own lock, which its users likewise recover from.
+fn lock_registry() -> MutexGuard<'static, HashMap<u64, Arc<Mutex<Tera>>>> {
+    TERA_ENGINES.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Looks up and clones the `Arc` for `handle`, holding the registry lock only
+/// for the lookup itself.
+fn clone_engine(handle: u64) -> Option<Arc<Mutex<Tera>>> {
+    lock_registry().get(&handle).cloned()
+}
+
+/// C signature for a host-provided Tera filter: takes the filtered value and
+/// its args, both as JSON, plus the opaque `user_data` passed at
+/// registration time, and returns the filtered value as JSON via the usual
+/// `ResultCString` convention.
+pub type TeraFilterCallback =
+    extern "C" fn(value_json: *const c_char, args_json: *const c_char, user_data: *mut c_void) -> ResultCString;
+
+/// C signature for a host-provided Tera function: takes the call args as
+/// JSON plus `user_data`, and returns the result as JSON via the usual
+/// `ResultCString` convention.
+pub type TeraFunctionCallback =
+    extern "C" fn(args_json: *const c_char, user_data: *mut c_void) -> ResultCString;
+
+/// Wraps an opaque host pointer so it can be stored in a `Tera` registry,
+/// which requires filters and functions to be `Send + Sync`.
+///
+/// # Safety
+/// The host callback that receives this pointer back is responsible for its
+/// own thread-safety: Tera may invoke filters/functions from whichever
+/// thread calls `render`, so the host must ensure `user_data` can be read
+/// (and, if mutated, synchronized) from any thread.
+struct CallbackUserData(*mut c_void);
+unsafe impl Send for CallbackUserData {}
+unsafe impl Sync for CallbackUserData {}
+
+/// Converts a `ResultCString` returned by a host callback into an owned
+/// `Result`, taking ownership of (and freeing) the underlying C string
+/// exactly once.
+fn consume_result_cstring(result: ResultCString) -> Result<String, String> {
+    match result {
+        ResultCString::Ok(ptr) => {
+            let value = c_char_to_string(ptr).unwrap_or_default();
+            unsafe {
+                if !ptr.is_null() {
+                    let _ = CString::from_raw(ptr);
+                }
+            }
+            Ok(value)
+        }
+        ResultCString::Err(ptr) => {
+            let message = c_char_to_string(ptr).unwrap_or_else(|| "callback returned an error".to_string());
+            unsafe {
+                if !ptr.is_null() {
+                    let _ = CString::from_raw(ptr);
+                }
+            }
+            Err(message)
+        }
+    }
+}
+
+/// Serializes a JSON `Value` to a `CString`, or turns the serialization
+/// failure into a `tera::Error`.
+fn value_to_json_cstring(value: &Value, what: &str) -> tera::Result<CString> {
+    let json = serde_json::to_string(value).map_err(|e| tera::Error::msg(format!("Failed to serialize {}: {}", what, e)))?;
+    CString::new(json).map_err(|e| tera::Error::msg(format!("{} JSON contained a NUL byte: {}", what, e)))
+}
+
+/// Serializes a filter/function args map to a `CString`, or turns the
+/// serialization failure into a `tera::Error`.
+fn args_to_json_cstring(args: &HashMap<String, Value>, what: &str) -> tera::Result<CString> {
+    let json = serde_json::to_string(args).map_err(|e| tera::Error::msg(format!("Failed to serialize {}: {}", what, e)))?;
+    CString::new(json).map_err(|e| tera::Error::msg(format!("{} JSON contained a NUL byte: {}", what, e)))
+}
+
+/// A `tera::Filter` that forwards to a host-registered C callback.
+struct CallbackFilter {
+    callback: TeraFilterCallback,
+    user_data: CallbackUserData,
+}
+
+impl tera::Filter for CallbackFilter {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let value_cstring = value_to_json_cstring(value, "filter value")?;
+        let args_cstring = args_to_json_cstring(args, "filter args")?;
+
+        let result = (self.callback)(value_cstring.as_ptr(), args_cstring.as_ptr(), self.user_data.0);
+
+        let output = consume_result_cstring(result).map_err(tera::Error::msg)?;
+        serde_json::from_str(&output).map_err(|e| tera::Error::msg(format!("Invalid JSON returned by filter callback: {}", e)))
+    }
+}
+
+/// A `tera::Function` that forwards to a host-registered C callback.
+struct CallbackFunction {
+    callback: TeraFunctionCallback,
+    user_data: CallbackUserData,
+}
+
+impl tera::Function for CallbackFunction {
+    fn call(&self, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let args_cstring = args_to_json_cstring(args, "function args")?;
+
+        let result = (self.callback)(args_cstring.as_ptr(), self.user_data.0);
+
+        let output = consume_result_cstring(result).map_err(tera::Error::msg)?;
+        serde_json::from_str(&output).map_err(|e| tera::Error::msg(format!("Invalid JSON returned by function callback: {}", e)))
+    }
+}
+
 macro_rules! make_str {
     ( $s : expr , $len : expr ) => {
         unsafe { str::from_utf8_unchecked(slice::from_raw_parts($s as *const u8, $len)) }
     };
 }
 
+/// Formats an error together with its full `source()` chain, matching the
+/// nested "Caused by:" reporting already used for render errors.
+fn format_chained_error(prefix: &str, error: &dyn Error) -> String {
+    let mut messages = vec![format!("{}: {}", prefix, error)];
+
+    let mut source_opt = error.source();
+    while let Some(source) = source_opt {
+        messages.push(format!("Caused by: {}", source));
+        source_opt = source.source();
+    }
+
+    messages.join("\n")
+}
+
+/// Applies the autoescape configuration conveyed across FFI as a bool plus
+/// an array of extension strings to an already-constructed `Tera` instance
+/// that will be used and dropped before the enclosing `render_template` /
+/// `render_template_buffer` call returns. The suffix strings are borrowed,
+/// not copied, so they must stay valid for the duration of that call.
+fn configure_autoescape(
+    tera: &mut Tera,
+    autoescape: bool,
+    autoescape_on: *const *const c_char,
+    autoescape_on_count: usize,
+) {
+    if !autoescape || autoescape_on_count == 0 {
+        tera.autoescape_on(vec![]);
+        return;
+    }
+
+    unsafe {
+        // create a slice of *const c_char
+        let slice: &[*const c_char] = std::slice::from_raw_parts(autoescape_on, autoescape_on_count);
+
+        // convert each C string to &str
+        let mut autoescape_on_vec: Vec<&str> = Vec::with_capacity(autoescape_on_count);
+        for &ptr in slice {
+            if ptr.is_null() {
+                continue;
+            }
+            let s = CStr::from_ptr(ptr).to_str().unwrap_or_default();
+            autoescape_on_vec.push(s);
+        }
+
+        // Transmute the lifetime to 'static. Sound only because `tera` does
+        // not outlive the enclosing render call: nothing else retains the `Vec<&'static
+        // str>` once `tera` is dropped, so the borrow never outlives the
+        // caller-owned `autoescape_on` buffer it actually points into.
+        tera.autoescape_on(std::mem::transmute::<Vec<&str>, Vec<&'static str>>(autoescape_on_vec));
+    }
+}
+
+/// Applies the autoescape configuration the same way as `configure_autoescape`,
+/// but for a `Tera` instance that will be stashed in the long-lived engine
+/// registry and reused across calls long after this one returns. Each
+/// suffix is copied into an owned, intentionally leaked `&'static str`
+/// rather than borrowed from the caller's `autoescape_on` buffer, since that
+/// buffer is not guaranteed to outlive the engine (unlike the one-shot
+/// `render_template` path that `configure_autoescape` serves).
+fn configure_autoescape_owned(
+    tera: &mut Tera,
+    autoescape: bool,
+    autoescape_on: *const *const c_char,
+    autoescape_on_count: usize,
+) {
+    if !autoescape || autoescape_on_count == 0 {
+        tera.autoescape_on(vec![]);
+        return;
+    }
+
+    unsafe {
+        let slice: &[*const c_char] = std::slice::from_raw_parts(autoescape_on, autoescape_on_count);
+
+        let autoescape_on_vec: Vec<&'static str> = slice
+            .iter()
+            .filter(|&&ptr| !ptr.is_null())
+            .map(|&ptr| {
+                let s = CStr::from_ptr(ptr).to_str().unwrap_or_default();
+                Box::leak(s.to_string().into_boxed_str()) as &'static str
+            })
+            .collect();
+
+        tera.autoescape_on(autoescape_on_vec);
+    }
+}
+
+/// Serialization format of a context string, as conveyed across FFI by a
+/// plain `u32`. `Json` is `0` so existing callers that leave the argument
+/// zeroed keep today's behavior.
+#[repr(u32)]
+#[derive(Clone, Copy)]
+pub enum ContextFormat {
+    Json = 0,
+    Yaml = 1,
+    Toml = 2,
+}
+
+impl ContextFormat {
+    fn from_u32(format: u32) -> Result<Self, String> {
+        match format {
+            0 => Ok(ContextFormat::Json),
+            1 => Ok(ContextFormat::Yaml),
+            2 => Ok(ContextFormat::Toml),
+            other => Err(format!("Unknown context format: {}", other)),
+        }
+    }
+}
+
+/// Parses a context string in the given `ContextFormat` into a Tera
+/// `Context`, mirroring the object traversal `render_template` has always
+/// used for JSON.
+fn context_from_str(input: &str, format: ContextFormat) -> Result<Context, String> {
+    let context_value: Value = match format {
+        ContextFormat::Json => {
+            serde_json::from_str(input).map_err(|e| format!("Invalid JSON: {}", e))?
+        }
+        ContextFormat::Yaml => {
+            serde_yaml::from_str(input).map_err(|e| format!("Invalid YAML: {}", e))?
+        }
+        ContextFormat::Toml => {
+            toml::from_str(input).map_err(|e| format!("Invalid TOML: {}", e))?
+        }
+    };
+
+    let mut context = Context::new();
+    if let Value::Object(map) = context_value {
+        for (key, value) in map {
+            context.insert(&key, &value);
+        }
+    }
+
+    Ok(context)
+}
+
 fn c_char_to_string(template_path: *const c_char) -> Option<String> {
     if template_path.is_null() {
         return None;
@@ -36,124 +347,394 @@ fn c_char_to_string(template_path: *const c_char) -> Option<String> {
 }
 
 
-/// Renders a Tera template from a file or a string with context variables provided as JSON.
+/// Shared implementation behind `render_template` and `render_template_buffer`.
+fn render_template_impl(
+    template_str: &str,
+    json_str: &str,
+    context_format: u32,
+    template_path_str: Option<String>,
+    autoescape: bool,
+    autoescape_on: *const *const c_char,
+    autoescape_on_count: usize,
+) -> Result<String, String> {
+    let format = ContextFormat::from_u32(context_format)?;
+    let context = context_from_str(json_str, format)?;
+
+    // Render the template
+
+    let result = match template_path_str {
+        Some(ref path) if !path.is_empty() => {
+
+        let mut tera = Tera::new(path.as_str())
+            .map_err(|e| format_chained_error("Template loading error", &e))?;
+
+        configure_autoescape(&mut tera, autoescape, autoescape_on, autoescape_on_count);
+
+        // Get the first template name and render it
+        tera.render(template_str, &context)
+
+        }
+        _ => {
+            // Render from string directly
+        Tera::one_off(template_str, &context, autoescape)
+
+        }
+    };
+
+    result.map_err(|error| format_chained_error("Tera render error", &error))
+}
+
+/// Renders a Tera template from a file or a string with context variables provided as JSON, YAML, or TOML.
+///
+/// Kept under its original NUL-terminated `ResultCString` convention for
+/// existing callers; see `render_template_buffer` for the length-explicit,
+/// binary-safe counterpart. Both call `render_template_impl` independently,
+/// so a change to one does not automatically apply to the other.
 ///
 /// # Arguments
 /// * `template_source` - Either the filename of the template or the template content as a string.
 /// * `from_file` - If true, treat `template_source` as a filename, otherwise as template content.
-/// * `json_context` - JSON string containing context variables.
+/// * `json_context` - Context string, serialized per `context_format`.
+/// * `context_format` - A `ContextFormat` value; `0` (JSON) is the default, preserving prior behavior.
 ///
 /// # Returns
-/// * `Ok(String)` containing the rendered template, or `Err(tera::Error)` if rendering fails.
+/// * A `ResultCString` carrying the rendered template (or error message) as a NUL-terminated string.
 #[no_mangle]
 pub extern "C" fn render_template(
     template_source: *const c_char,
     template_source_len: usize,
     json_context: *const c_char,
     json_context_len: usize,
+    context_format: u32,
     template_path: *const c_char,
     autoescape: bool,
     autoescape_on: *const *const c_char,
     autoescape_on_count: usize
 ) -> ResultCString {
+    let template_str = make_str!(template_source, template_source_len);
+    let json_str = make_str!(json_context, json_context_len);
+    let template_path_str = c_char_to_string(template_path);
+
+    match render_template_impl(
+        template_str,
+        json_str,
+        context_format,
+        template_path_str,
+        autoescape,
+        autoescape_on,
+        autoescape_on_count,
+    ) {
+        Ok(output) => match CString::new(output) {
+            Ok(c) => ResultCString::Ok(c.into_raw()),
+            Err(e) => cstring_err(format!("Rendered output contained an interior NUL byte: {}", e)),
+        },
+        Err(msg) => cstring_err(msg),
+    }
+}
 
+/// Length-explicit, binary-safe counterpart to `render_template` for callers
+/// that can take a `ResultBuffer` instead of a NUL-terminated `ResultCString`.
+/// Unlike `render_template` this never panics or errors on interior NUL
+/// bytes and can carry arbitrary binary template output.
+///
+/// # Arguments / Returns
+/// Same as `render_template`, but the result is a `ResultBuffer`.
+#[no_mangle]
+pub extern "C" fn render_template_buffer(
+    template_source: *const c_char,
+    template_source_len: usize,
+    json_context: *const c_char,
+    json_context_len: usize,
+    context_format: u32,
+    template_path: *const c_char,
+    autoescape: bool,
+    autoescape_on: *const *const c_char,
+    autoescape_on_count: usize
+) -> ResultBuffer {
     let template_str = make_str!(template_source, template_source_len);
     let json_str = make_str!(json_context, json_context_len);
     let template_path_str = c_char_to_string(template_path);
 
-    // Parse the JSON string into a serde_json::Value
-    let context_value: Value = match serde_json::from_str(json_str) {
-        Ok(val) => val,
+    match render_template_impl(
+        template_str,
+        json_str,
+        context_format,
+        template_path_str,
+        autoescape,
+        autoescape_on,
+        autoescape_on_count,
+    ) {
+        Ok(output) => buffer_ok(output.into_bytes()),
+        Err(msg) => buffer_err(msg),
+    }
+}
+
+/// Parses and stores a `Tera` engine once, returning a handle that
+/// `tera_engine_render` can reuse across many renders without re-globbing or
+/// re-parsing the template set.
+///
+/// # Arguments
+/// * `glob_path` - Glob pattern matching the template files to load, as used by `Tera::new`.
+/// * `autoescape` / `autoescape_on` / `autoescape_on_count` - Same autoescape configuration as `render_template`.
+///   Unlike `render_template`, the `autoescape_on` strings are copied before this call returns, so the caller
+///   does not need to keep that buffer alive for the lifetime of the engine.
+///
+/// # Returns
+/// * `ResultHandle::Ok` with the engine handle on success, or `ResultHandle::Err` with a chained error message on failure.
+#[no_mangle]
+pub extern "C" fn tera_engine_create(
+    glob_path: *const c_char,
+    glob_path_len: usize,
+    autoescape: bool,
+    autoescape_on: *const *const c_char,
+    autoescape_on_count: usize,
+) -> ResultHandle {
+    let glob_str = make_str!(glob_path, glob_path_len);
+
+    let mut tera = match Tera::new(glob_str) {
+        Ok(t) => t,
         Err(e) => {
-            let error_msg = format!("Invalid JSON: {}", e);
-            let error_str = CString::new(error_msg).unwrap();
-            return ResultCString::Err(error_str.into_raw());
+            let error_str = CString::new(format_chained_error("Template loading error", &e)).unwrap();
+            return ResultHandle::Err(error_str.into_raw());
         }
     };
 
-    // Convert the JSON Value into a Tera Context
-    let mut context = Context::new();
-    if let Value::Object(map) = context_value {
-        for (key, value) in map {
-            context.insert(&key, &value);
-        }
-    }
+    configure_autoescape_owned(&mut tera, autoescape, autoescape_on, autoescape_on_count);
 
+    let handle = NEXT_ENGINE_HANDLE.fetch_add(1, Ordering::Relaxed);
+    lock_registry().insert(handle, Arc::new(Mutex::new(tera)));
 
-    // Render the template
+    ResultHandle::Ok(handle)
+}
 
-    let result = match template_path_str {
-        Some(ref path) if !path.is_empty() => {
+/// Adds a named template to a previously created engine entirely from
+/// memory, via `Tera::add_raw_template`. This lets callers with no backing
+/// template directory (an embedded or sandboxed deployment) push base
+/// layouts, partials, and child templates by name and still use
+/// `{% extends %}`/`{% include %}`, which `Tera::one_off` cannot resolve.
+///
+/// # Arguments
+/// * `handle` - Engine handle returned by `tera_engine_create`.
+/// * `name` - Name the template is registered under, as referenced by `{% extends %}`/`{% include %}` and by `tera_engine_render`.
+/// * `content` - Template source.
+///
+/// # Returns
+/// * A `ResultCString` with an empty payload on success, or a chained parse error if the template is invalid or the handle is unknown.
+///
+/// # Safety
+/// `name` and `content` must point to at least `name_len`/`content_len`
+/// valid UTF-8 bytes for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn tera_engine_add_raw_template(
+    handle: u64,
+    name: *const c_char,
+    name_len: usize,
+    content: *const c_char,
+    content_len: usize,
+) -> ResultCString {
+    let name_str = make_str!(name, name_len);
+    let content_str = make_str!(content, content_len);
 
-        let mut tera = match Tera::new(path.as_str()) {
-            Ok(t) => t,
-            Err(e) => {
-                let error_msg = format!("Template loading error: {}", e);
-                let error_str = CString::new(error_msg).unwrap();
-                return ResultCString::Err(error_str.into_raw());
-            }
-        };
+    let engine = match clone_engine(handle) {
+        Some(engine) => engine,
+        None => return cstring_err(format!("Unknown Tera engine handle: {}", handle)),
+    };
+    let mut tera = engine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match tera.add_raw_template(name_str, content_str) {
+        Ok(()) => ResultCString::Ok(CString::new("").unwrap().into_raw()),
+        Err(e) => cstring_err(format_chained_error("Template parse error", &e)),
+    }
+}
 
+/// Shared implementation behind `tera_engine_render` and `tera_engine_render_cstring`.
+fn tera_engine_render_impl(handle: u64, template_name: &str, json_str: &str, context_format: u32) -> Result<String, String> {
+    let format = ContextFormat::from_u32(context_format)?;
+    let context = context_from_str(json_str, format)?;
 
-        if !autoescape || autoescape_on_count == 0 {
-            tera.autoescape_on(vec![]);
-        } else if autoescape && autoescape_on_count > 0 {
+    let engine = clone_engine(handle).ok_or_else(|| format!("Unknown Tera engine handle: {}", handle))?;
+    let tera = engine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
 
-            unsafe {
-                // create a slice of *const c_char
-                let slice: &[*const c_char] = std::slice::from_raw_parts(autoescape_on, autoescape_on_count);
-
-                // convert each C string to &str
-                let mut autoescape_on_vec: Vec<&str> = Vec::with_capacity(autoescape_on_count);
-                for &ptr in slice {
-                    if ptr.is_null() {
-                        continue;
-                    }
-                    let s = CStr::from_ptr(ptr).to_str().unwrap_or_default();
-                    autoescape_on_vec.push(s);
-                }
+    tera.render(template_name, &context)
+        .map_err(|error| format_chained_error("Tera render error", &error))
+}
 
-                // Transmute the lifetime to 'static
+/// Renders a template by name from a previously created engine.
+///
+/// # Arguments
+/// * `handle` - Engine handle returned by `tera_engine_create`.
+/// * `template_name` - Name of the template to render, as registered by the glob used at creation or by `tera_engine_add_raw_template`.
+/// * `json_context` - Context string, serialized per `context_format`.
+/// * `context_format` - A `ContextFormat` value; `0` (JSON) is the default, preserving prior behavior.
+///
+/// # Returns
+/// * A `ResultBuffer` carrying the rendered template (or error message) as binary-safe bytes.
+#[no_mangle]
+pub extern "C" fn tera_engine_render(
+    handle: u64,
+    template_name: *const c_char,
+    template_name_len: usize,
+    json_context: *const c_char,
+    json_context_len: usize,
+    context_format: u32,
+) -> ResultBuffer {
+    let template_name_str = make_str!(template_name, template_name_len);
+    let json_str = make_str!(json_context, json_context_len);
 
-                tera.autoescape_on(std::mem::transmute::<Vec<&str>, Vec<&'static str>>(autoescape_on_vec));
-            }
-        }
+    match tera_engine_render_impl(handle, template_name_str, json_str, context_format) {
+        Ok(output) => buffer_ok(output.into_bytes()),
+        Err(msg) => buffer_err(msg),
+    }
+}
 
-        // Get the first template name and render it
-        tera.render(template_str, &context)
+/// Thin NUL-terminated compatibility shim over `tera_engine_render` for
+/// callers still built against the `ResultCString` convention.
+///
+/// # Arguments / Returns
+/// Same as `tera_engine_render`.
+#[no_mangle]
+pub extern "C" fn tera_engine_render_cstring(
+    handle: u64,
+    template_name: *const c_char,
+    template_name_len: usize,
+    json_context: *const c_char,
+    json_context_len: usize,
+    context_format: u32,
+) -> ResultCString {
+    let template_name_str = make_str!(template_name, template_name_len);
+    let json_str = make_str!(json_context, json_context_len);
 
-        }
-        _ => {
-            // Render from string directly
-        Tera::one_off(template_str, &context, autoescape)
+    match tera_engine_render_impl(handle, template_name_str, json_str, context_format) {
+        Ok(output) => match CString::new(output) {
+            Ok(c) => ResultCString::Ok(c.into_raw()),
+            Err(e) => cstring_err(format!("Rendered output contained an interior NUL byte: {}", e)),
+        },
+        Err(msg) => cstring_err(msg),
+    }
+}
 
+/// Registers a host-provided filter callback on a previously created engine
+/// under `name`. The callback is invoked with the filtered value and its
+/// args as JSON; it must return the filtered value as JSON through the
+/// usual `ResultCString` convention.
+///
+/// # Arguments
+/// * `handle` - Engine handle returned by `tera_engine_create`.
+/// * `name` - Name the filter is registered under, as used in `{{ value | name }}`.
+/// * `callback` - C function pointer invoked on each use of the filter.
+/// * `user_data` - Opaque pointer passed back to `callback` on every call.
+///
+/// # Safety
+/// `callback` must be safe to call from whichever thread renders templates
+/// through this engine, and must treat `user_data` accordingly if it reads
+/// or mutates state behind it. The callback and `user_data` must remain
+/// valid for the lifetime of the engine, i.e. until `tera_engine_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tera_engine_register_filter(
+    handle: u64,
+    name: *const c_char,
+    name_len: usize,
+    callback: TeraFilterCallback,
+    user_data: *mut c_void,
+) -> ResultCString {
+    let name_str = make_str!(name, name_len);
+
+    let engine = match clone_engine(handle) {
+        Some(engine) => engine,
+        None => {
+            let error_str = CString::new(format!("Unknown Tera engine handle: {}", handle)).unwrap();
+            return ResultCString::Err(error_str.into_raw());
         }
     };
+    let mut tera = engine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    tera.register_filter(
+        name_str,
+        CallbackFilter {
+            callback,
+            user_data: CallbackUserData(user_data),
+        },
+    );
+    ResultCString::Ok(CString::new("").unwrap().into_raw())
+}
 
-    match result {
-        Ok(output) => {
-            let value_str = CString::new(output).unwrap();
-            ResultCString::Ok(value_str.into_raw())
-        }
-        Err(error) => {
-                        // Build a detailed error message
-            let mut messages = vec![format!("Tera render error: {}", error)];
-
-            // Include nested sources if any
-            let mut source_opt = error.source();
-            while let Some(source) = source_opt {
-                messages.push(format!("Caused by: {}", source));
-                source_opt = source.source();
-            }
+/// Registers a host-provided function callback on a previously created
+/// engine under `name`. The callback is invoked with the call args as JSON;
+/// it must return the result as JSON through the usual `ResultCString`
+/// convention.
+///
+/// # Arguments
+/// * `handle` - Engine handle returned by `tera_engine_create`.
+/// * `name` - Name the function is registered under, as used in `{{ name(arg=1) }}`.
+/// * `callback` - C function pointer invoked on each use of the function.
+/// * `user_data` - Opaque pointer passed back to `callback` on every call.
+///
+/// # Safety
+/// Same thread-safety and lifetime contract as `tera_engine_register_filter`.
+#[no_mangle]
+pub unsafe extern "C" fn tera_engine_register_function(
+    handle: u64,
+    name: *const c_char,
+    name_len: usize,
+    callback: TeraFunctionCallback,
+    user_data: *mut c_void,
+) -> ResultCString {
+    let name_str = make_str!(name, name_len);
 
-            let formatted_error = messages.join("\n");
+    let engine = match clone_engine(handle) {
+        Some(engine) => engine,
+        None => {
+            let error_str = CString::new(format!("Unknown Tera engine handle: {}", handle)).unwrap();
+            return ResultCString::Err(error_str.into_raw());
+        }
+    };
+    let mut tera = engine.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    tera.register_function(
+        name_str,
+        CallbackFunction {
+            callback,
+            user_data: CallbackUserData(user_data),
+        },
+    );
+    ResultCString::Ok(CString::new("").unwrap().into_raw())
+}
+
+/// Drops a previously created Tera engine, freeing its compiled templates.
+///
+/// # Safety
+/// The caller must ensure `handle` is not used by any in-flight
+/// `tera_engine_render` call and is not passed to this function again.
+#[no_mangle]
+pub unsafe extern "C" fn tera_engine_free(handle: u64) {
+    lock_registry().remove(&handle);
+}
 
-            let error_str = CString::new(formatted_error).unwrap();
-            ResultCString::Err(error_str.into_raw())
+/// Frees the memory allocated for a `ResultHandle::Err` error string.
+///
+/// # Safety
+/// Same ownership contract as `free_result_cstring`: must be called exactly
+/// once for each `ResultHandle` returned by `tera_engine_create`.
+#[no_mangle]
+pub unsafe extern "C" fn free_result_handle(result: ResultHandle) {
+    if let ResultHandle::Err(ptr) = result {
+        if !ptr.is_null() {
+            let _ = CString::from_raw(ptr);
         }
     }
 }
 
+/// Frees the memory allocated for a `ResultBuffer`.
+///
+/// # Safety
+/// Same ownership contract as `free_result_cstring`: the buffer must have
+/// been produced by this library's `ResultBuffer`-returning functions and
+/// must be freed exactly once.
+#[no_mangle]
+pub unsafe extern "C" fn free_result_buffer(buf: ResultBuffer) {
+    if buf.ptr.is_null() {
+        return;
+    }
+    let _ = Vec::from_raw_parts(buf.ptr, buf.len, buf.capacity);
+}
+
 /// Frees the memory allocated for a ResultCString.
 ///
 /// # Arguments
@@ -181,3 +762,167 @@ pub unsafe extern "C" fn free_result_cstring(result: ResultCString) {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    /// Creates an engine with a glob matching no files (`Tera::new` treats
+    /// an unmatched glob as an empty template set rather than an error), so
+    /// tests can register templates in-memory via `tera_engine_add_raw_template`.
+    fn create_test_engine() -> u64 {
+        let glob = CString::new("/nonexistent-tera-test-glob/*").unwrap();
+        match tera_engine_create(glob.as_ptr(), glob.as_bytes().len(), false, ptr::null(), 0) {
+            ResultHandle::Ok(handle) => handle,
+            ResultHandle::Err(err) => unsafe {
+                let message = CStr::from_ptr(err).to_string_lossy().into_owned();
+                free_result_handle(ResultHandle::Err(err));
+                panic!("tera_engine_create failed: {}", message);
+            },
+        }
+    }
+
+    fn add_raw_template(handle: u64, name: &str, content: &str) {
+        let name_c = CString::new(name).unwrap();
+        let content_c = CString::new(content).unwrap();
+        let result = unsafe {
+            tera_engine_add_raw_template(
+                handle,
+                name_c.as_ptr(),
+                name_c.as_bytes().len(),
+                content_c.as_ptr(),
+                content_c.as_bytes().len(),
+            )
+        };
+        match result {
+            ResultCString::Ok(ptr) => unsafe {
+                let _ = CString::from_raw(ptr);
+            },
+            ResultCString::Err(ptr) => unsafe {
+                let message = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+                let _ = CString::from_raw(ptr);
+                panic!("tera_engine_add_raw_template failed: {}", message);
+            },
+        }
+    }
+
+    fn render(handle: u64, template_name: &str, json_context: &str) -> Result<String, String> {
+        let name_c = CString::new(template_name).unwrap();
+        let json_c = CString::new(json_context).unwrap();
+        let buf = tera_engine_render(
+            handle,
+            name_c.as_ptr(),
+            name_c.as_bytes().len(),
+            json_c.as_ptr(),
+            json_c.as_bytes().len(),
+            0,
+        );
+        let bytes = unsafe { slice::from_raw_parts(buf.ptr, buf.len) }.to_vec();
+        let is_error = buf.is_error;
+        unsafe { free_result_buffer(buf) };
+        let text = String::from_utf8(bytes).unwrap();
+        if is_error {
+            Err(text)
+        } else {
+            Ok(text)
+        }
+    }
+
+    #[test]
+    fn engine_handle_lifecycle_renders_a_registered_template() {
+        let handle = create_test_engine();
+        add_raw_template(handle, "greeting", "Hello, {{ name }}!");
+
+        let output = render(handle, "greeting", r#"{"name": "World"}"#).unwrap();
+        assert_eq!(output, "Hello, World!");
+
+        unsafe { tera_engine_free(handle) };
+    }
+
+    #[test]
+    fn render_with_unknown_handle_reports_an_error() {
+        let result = render(999_999_999, "greeting", "{}");
+        let message = result.unwrap_err();
+        assert!(message.contains("Unknown Tera engine handle"), "unexpected message: {}", message);
+    }
+
+    #[test]
+    fn add_raw_template_with_unknown_handle_reports_an_error() {
+        let name = CString::new("greeting").unwrap();
+        let content = CString::new("hi").unwrap();
+        let result = unsafe {
+            tera_engine_add_raw_template(999_999_999, name.as_ptr(), name.as_bytes().len(), content.as_ptr(), content.as_bytes().len())
+        };
+        match result {
+            ResultCString::Err(ptr) => unsafe {
+                let message = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+                let _ = CString::from_raw(ptr);
+                assert!(message.contains("Unknown Tera engine handle"));
+            },
+            ResultCString::Ok(ptr) => unsafe {
+                let _ = CString::from_raw(ptr);
+                panic!("expected an error for an unknown handle");
+            },
+        }
+    }
+
+    extern "C" fn shout_filter(value_json: *const c_char, _args_json: *const c_char, _user_data: *mut c_void) -> ResultCString {
+        let value: Value = serde_json::from_str(&c_char_to_string(value_json).unwrap()).unwrap();
+        let shouted = format!("{}!!!", value.as_str().unwrap_or_default());
+        ResultCString::Ok(CString::new(Value::String(shouted).to_string()).unwrap().into_raw())
+    }
+
+    extern "C" fn add_function(args_json: *const c_char, _user_data: *mut c_void) -> ResultCString {
+        let args: HashMap<String, Value> = serde_json::from_str(&c_char_to_string(args_json).unwrap()).unwrap();
+        let a = args.get("a").and_then(Value::as_i64).unwrap_or(0);
+        let b = args.get("b").and_then(Value::as_i64).unwrap_or(0);
+        ResultCString::Ok(CString::new(Value::from(a + b).to_string()).unwrap().into_raw())
+    }
+
+    #[test]
+    fn registered_filter_and_function_callbacks_round_trip_through_render() {
+        let handle = create_test_engine();
+
+        let filter_name = CString::new("shout").unwrap();
+        let result = unsafe {
+            tera_engine_register_filter(handle, filter_name.as_ptr(), filter_name.as_bytes().len(), shout_filter, ptr::null_mut())
+        };
+        unsafe { free_result_cstring(result) };
+
+        let function_name = CString::new("add").unwrap();
+        let result = unsafe {
+            tera_engine_register_function(handle, function_name.as_ptr(), function_name.as_bytes().len(), add_function, ptr::null_mut())
+        };
+        unsafe { free_result_cstring(result) };
+
+        add_raw_template(handle, "main", "{{ name | shout }} {{ add(a=2, b=3) }}");
+
+        let output = render(handle, "main", r#"{"name": "hi"}"#).unwrap();
+        assert_eq!(output, "hi!!! 5");
+
+        unsafe { tera_engine_free(handle) };
+    }
+
+    #[test]
+    fn render_template_buffer_reconstructs_binary_safe_output() {
+        let template = CString::new("{{ name }}").unwrap();
+        let json = CString::new(r#"{"name": "World"}"#).unwrap();
+
+        let buf = render_template_buffer(
+            template.as_ptr(),
+            template.as_bytes().len(),
+            json.as_ptr(),
+            json.as_bytes().len(),
+            0,
+            ptr::null(),
+            false,
+            ptr::null(),
+            0,
+        );
+        assert!(!buf.is_error);
+        let bytes = unsafe { slice::from_raw_parts(buf.ptr, buf.len) }.to_vec();
+        unsafe { free_result_buffer(buf) };
+        assert_eq!(bytes, b"World");
+    }
+}
+